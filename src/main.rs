@@ -1,4 +1,6 @@
 extern crate image;
+extern crate freetype;
+extern crate gif;
 
 /* catpicture
  * @author Joseph Catrambone <jo.jcat@gmail.com>
@@ -11,6 +13,12 @@ extern crate image;
  * v0.6.0 : Allow threshold to be set for _not_ drawing, so if people want black text to show as empty space (for writing to text file), that can be done.
  * v0.7.0 : Introduce FFT to split high-frequency pixels from low frequency pixels. Draw high frequency in FG with font, low frequency in BG.
  * v1.0.0 : Ready for release.
+ * v1.1.0 : Add --stream mode to play raw video frames piped in from a tool like FFmpeg.
+ * v1.2.0 : Rasterize the 'art' mode comparison set from a real font via FreeType with --font/--font-size.
+ * v1.3.0 : Add halfblock draw mode for 2x vertical resolution using the upper-half-block character.
+ * v1.4.0 : Play animated GIFs in the terminal, honoring per-frame delay and --loop.
+ * v1.5.0 : Composite transparent pixels over a chosen --background instead of dropping the alpha channel.
+ * v1.6.0 : Add --psnr to report reconstruction fidelity against the source image.
  */
 
 use std::char;
@@ -18,45 +26,86 @@ use std::clone::Clone;
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::env;
+use std::fs;
 use std::io::{Cursor, Read, self};
 use std::option::Option;
 use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
-use image::{GenericImage, imageops, FilterType, DynamicImage, Pixel}; // Pixel used for .to_luma.
+use image::{GenericImage, ImageBuffer, imageops, FilterType, DynamicImage, Pixel, Rgb, Rgba, Luma}; // Pixel used for .to_luma.
+use gif::SetParameter; // Brings .set() into scope for decoder.set(gif::ColorOutput::RGBA) below.
 
 const COMPARISON_SET : &'static str = "characters.png";
 const DEFAULT_WIDTH : u32 = 80;
+const DEFAULT_FONT_SIZE : u32 = 16;
 const LINE_ALGORITHM : &'static str = "-d";
 const USE_FULL_COLORS : &'static str = "-c";
 const OUTPUT_WIDTH : &'static str = "-w";
 const OUTPUT_HEIGHT : &'static str = "-h";
 const SOURCE_RECT : &'static str = "-r";
 const FORCE_GREY : &'static str = "-g";
+const STREAM_MODE : &'static str = "--stream";
+const FONT_PATH : &'static str = "--font";
+const FONT_SIZE : &'static str = "--font-size";
+const LOOP_COUNT : &'static str = "--loop";
+const BACKGROUND_COLOR : &'static str = "--background";
+const PSNR : &'static str = "--psnr";
 const HELP_SHORT : &'static str = "-?";
 const HELP_LONG : &'static str = "--help";
 const HELP_STRING : &'static str = r#"
-Usage: 
-catpicture [--help|-?] [-c] [-w] [-h] [-r x1 y1 x2 y2] [-g] [-d block|art|char x] [filename]
+Usage:
+catpicture [--help|-?] [-c] [-w] [-h] [-r x1 y1 x2 y2] [-g] [-d block|art|char x|halfblock] [--stream WxH fmt] [--font path] [--font-size px] [--loop N] [--background r,g,b] [--psnr] [filename]
 --help/-?	This message.
--c	Try to use full color instead of nearest XTERM color. 
+-c	Try to use full color instead of nearest XTERM color.
 -w	Set output width.
 -h	Set output height.
 -r xywh	Given four points (left top right bottom), cut the specified region from the picture for display.
 -g	Force greyscale on image.
--d	Specify the 'draw mode' for the output. 
+-d	Specify the 'draw mode' for the output.
 		block -> Only background will be filled.
 		art -> Use nearest neighbor to find the best approximate character match for a patch.
 		char -> Use the specified character to draw.
+		halfblock -> Use the upper-half-block character to pack two vertical pixels into one cell, doubling vertical resolution.
+--stream WxH fmt	Read fixed-size raw frames from stdin and play them back in place (e.g. piped from `ffmpeg -f rawvideo`).
+		fmt is one of: gray8 (1 byte/pixel), rgb24 (3 bytes/pixel).
+--font path	Rasterize the 'art' mode comparison set from this TrueType/PCF font via FreeType instead of the built-in character set.
+--font-size px	Pixel size to render glyphs at when --font is given.  Defaults to 16.
+--loop N	When the input is an animated GIF, loop playback N times.  0 means loop forever.  Defaults to 1.
+--background r,g,b	Composite transparent pixels over this color instead of dropping the alpha channel.  Defaults to black.
+--psnr	After rendering, print the PSNR (in dB) between the rendered output and the source image to stderr.
+		Only Art mode is scored as losing fidelity (a glyph's average brightness stands in for the
+		sampled pixel); Block, Char, and HalfBlock are scored with the sampled color itself, so
+		the nearest-XTERM-color substitution made without -c is not counted and they always
+		report "inf dB".
 filename	The name of the image to open.  If unspecified, reads from stdin.
 "#;
 
+#[derive(Clone, Copy, PartialEq)]
+enum PixelFormat {
+	Gray8,
+	Rgb24,
+}
+
+impl PixelFormat {
+	fn bytes_per_pixel(&self) -> u32 {
+		match *self {
+			PixelFormat::Gray8 => 1,
+			PixelFormat::Rgb24 => 3,
+		}
+	}
+}
+
 #[derive(PartialEq)]
 enum DrawMode {
 	Block,
 	Char(char),
 	Art,
+	HalfBlock,
 }
 
+const HALF_BLOCK_CHAR : char = '\u{2580}'; // Upper half block: fg = top pixel, bg = bottom pixel.
+
 struct Settings {
 	input_filename : String, // Will be "" for stdin.
 	output_width : Option<u32>,
@@ -66,6 +115,12 @@ struct Settings {
 	show_help : bool,
 	force_grey : bool,
 	draw_mode : DrawMode,
+	stream_mode : Option<(u32, u32, PixelFormat)>,
+	font_path : Option<String>,
+	font_size : u32,
+	loop_count : u32,
+	background : Option<(u8, u8, u8)>,
+	psnr : bool,
 }
 
 fn parse_args(args : Vec<String>) -> Settings {
@@ -78,6 +133,12 @@ fn parse_args(args : Vec<String>) -> Settings {
 		use_full_colors : false,
 		force_grey : false,
 		draw_mode : DrawMode::Block,
+		stream_mode : None,
+		font_path : None,
+		font_size : DEFAULT_FONT_SIZE,
+		loop_count : 1,
+		background : None,
+		psnr : false,
 	};
 
 	let mut skip_args = 0; // True if the argument was consumed.
@@ -111,6 +172,7 @@ fn parse_args(args : Vec<String>) -> Settings {
 			settings.draw_mode = match mode.as_ref() {
 				"block" => DrawMode::Block,
 				"art" => DrawMode::Art,
+				"halfblock" => DrawMode::HalfBlock,
 				"char" => {
 					skip_args = 1;
 					DrawMode::Char(args[i+2].chars().nth(0).unwrap())
@@ -131,6 +193,36 @@ fn parse_args(args : Vec<String>) -> Settings {
 			skip_args = 4;
 		} else if arg == FORCE_GREY {
 			settings.force_grey = true;
+		} else if arg == STREAM_MODE {
+			let dimensions = &args[i+1].to_lowercase();
+			let mut dimension_parts = dimensions.split('x');
+			let stream_width = dimension_parts.next().unwrap().parse::<u32>().unwrap();
+			let stream_height = dimension_parts.next().unwrap().parse::<u32>().unwrap();
+			let format = match args[i+2].to_lowercase().as_ref() {
+				"gray8" => PixelFormat::Gray8,
+				"rgb24" => PixelFormat::Rgb24,
+				other => panic!("Unrecognized stream pixel format: {}", other),
+			};
+			settings.stream_mode = Some((stream_width, stream_height, format));
+			skip_args = 2;
+		} else if arg == FONT_PATH {
+			settings.font_path = Some(args[i+1].to_string());
+			skip_args = 1;
+		} else if arg == FONT_SIZE {
+			settings.font_size = args[i+1].parse::<u32>().unwrap();
+			skip_args = 1;
+		} else if arg == LOOP_COUNT {
+			settings.loop_count = args[i+1].parse::<u32>().unwrap();
+			skip_args = 1;
+		} else if arg == BACKGROUND_COLOR {
+			let mut components = args[i+1].split(',');
+			let r = components.next().unwrap().parse::<u8>().unwrap();
+			let g = components.next().unwrap().parse::<u8>().unwrap();
+			let b = components.next().unwrap().parse::<u8>().unwrap();
+			settings.background = Some((r, g, b));
+			skip_args = 1;
+		} else if arg == PSNR {
+			settings.psnr = true;
 		} else {
 			if settings.input_filename == "" && args[i].chars().nth(0).unwrap_or('-') != '-' {
 				settings.input_filename = args[i].to_string();
@@ -255,66 +347,418 @@ fn find_best_character(x : u32, y : u32, w : u32, h : u32, input_image : &Dynami
 	best_char
 }
 
+// Reads one grayscale sample out of a raw FreeType bitmap buffer, handling both the 8-bit-per-pixel
+// `Gray` format FreeType produces for outline (TrueType) glyphs and the 1-bit-per-pixel `Mono`
+// format embedded-bitmap (PCF/BDF) faces come back as instead -- FT_Render_Glyph is a no-op for
+// faces that are already bitmaps, so those glyphs never get upsampled to Gray for us.  `stride` is
+// the bitmap's pitch (bytes per row), which for Mono is ceil(width/8) and NOT the same as width.
+fn sample_glyph_pixel(buffer : &[u8], stride : u32, pixel_mode : freetype::bitmap::PixelMode, px : u32, py : u32) -> u8 {
+	match pixel_mode {
+		freetype::bitmap::PixelMode::Mono => {
+			let byte = buffer[(py * stride + px / 8) as usize];
+			let bit = 7 - (px % 8);
+			if (byte >> bit) & 1 == 1 { 255 } else { 0 }
+		},
+		_ => buffer[(py * stride + px) as usize],
+	}
+}
+
+// Rasterizes ' ' through '~' from a real font via FreeType into the same shape that
+// build_character_image_vector produces from the baked-in character sheet: one equally-sized
+// DynamicImage per glyph, indexed by (char - ' '). Works for both TrueType and PCF faces since
+// FreeType abstracts the outline/bitmap format away from us.
+fn build_character_image_vector_from_font(font_path : &str, font_size : u32) -> Vec<DynamicImage> {
+	let library = freetype::Library::init().unwrap();
+	let face = library.new_face(font_path, 0).unwrap();
+	face.set_pixel_sizes(0, font_size).unwrap();
+
+	let metrics = face.size_metrics().unwrap();
+	let cell_width = (metrics.max_advance >> 6) as u32;
+	let cell_height = (metrics.height >> 6) as u32;
+	let ascender = (metrics.ascender >> 6) as i32;
+
+	let num_characters : u32 = (b'~' - b' ') as u32 + 1; // Inclusive of '~'.
+	let mut characters = Vec::with_capacity(num_characters as usize);
+	for i in 0..num_characters {
+		let c = char::from_u32(i + b' ' as u32).unwrap();
+		face.load_char(c as usize, freetype::face::LoadFlag::RENDER).unwrap();
+		let glyph = face.glyph();
+		let bitmap = glyph.bitmap();
+		let bitmap_width = bitmap.width() as u32;
+		let bitmap_height = bitmap.rows() as u32;
+		let stride = bitmap.pitch().abs() as u32;
+		let pixel_mode = bitmap.pixel_mode().unwrap();
+		let bitmap_buffer = bitmap.buffer();
+
+		let mut cell = ImageBuffer::<Luma<u8>, Vec<u8>>::new(cell_width.max(1), cell_height.max(1));
+		let left = glyph.bitmap_left().max(0) as u32;
+		let top = (ascender - glyph.bitmap_top()).max(0) as u32;
+		for py in 0..bitmap_height {
+			for px in 0..bitmap_width {
+				let dest_x = left + px;
+				let dest_y = top + py;
+				if dest_x < cell_width && dest_y < cell_height {
+					let value = sample_glyph_pixel(bitmap_buffer, stride, pixel_mode, px, py);
+					cell.put_pixel(dest_x, dest_y, Luma([value]));
+				}
+			}
+		}
+		characters.push(DynamicImage::ImageLuma8(cell));
+	}
+	characters
+}
+
+// Loads the comparison character set used by the 'Art' draw mode.  Pulled out of main() so both
+// the single-image path and the streaming path can share it instead of re-decoding it per frame.
+// Uses a font rasterized live via FreeType when --font is given, falling back to the baked-in
+// character sheet otherwise.
+fn load_character_set(settings : &Settings) -> Vec<DynamicImage> {
+	match settings.font_path {
+		Some(ref font_path) => build_character_image_vector_from_font(font_path, settings.font_size),
+		None => {
+			let font_image = image::load(Cursor::new(&include_bytes!("characters.png")[..]), image::PNG).unwrap(); // TODO: MAGIC NUMBER - Make 'characters' a magic number.
+			build_character_image_vector(&font_image)
+		},
+	}
+}
+
+// Composites a single channel's pixel value over the background using the standard "over" operator.
+fn blend_channel(fg : u8, bg : u8, alpha : u8) -> u8 {
+	((fg as u32 * alpha as u32 + bg as u32 * (255 - alpha as u32)) / 255) as u8
+}
+
+// Extracts a pixel's color, compositing alpha over `background` and collapsing to grey if asked.
+fn extract_rgb(pixel : &image::Rgba<u8>, force_grey : bool, background : (u8, u8, u8)) -> (u8, u8, u8) {
+	let alpha = pixel.data[3];
+	let mut rgb = (
+		blend_channel(pixel.data[0], background.0, alpha),
+		blend_channel(pixel.data[1], background.1, alpha),
+		blend_channel(pixel.data[2], background.2, alpha),
+	);
+	if force_grey {
+		// TODO: Check if already luma and use to_luma.
+		let sum_rgb : u8 = ((rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3) as u8;
+		rgb = (sum_rgb, sum_rgb, sum_rgb);
+	}
+	rgb
+}
+
+fn luma_of_rgb(rgb : (u8, u8, u8)) -> f64 {
+	0.299 * rgb.0 as f64 + 0.587 * rgb.1 as f64 + 0.114 * rgb.2 as f64
+}
+
+fn mean_glyph_luma(glyph : &DynamicImage) -> f64 {
+	let (w, h) = glyph.dimensions();
+	let mut sum : u64 = 0;
+	for py in 0..h {
+		for px in 0..w {
+			sum += glyph.get_pixel(px, py).to_luma().data[0] as u64;
+		}
+	}
+	sum as f64 / (w * h) as f64
+}
+
+// Reports how faithfully the rendered output reproduces the source, as PSNR in dB, over stderr.
+// Only Art mode substitutes anything lossy (a glyph's average brightness for the sampled pixel's
+// luma) -- Block, Char, and HalfBlock record the sampled color itself as their reconstruction
+// (the nearest-XTERM-color substitution made without -c is not counted), so reconstructed_luma
+// and target_luma are identical for them and this will always report "inf dB".
+fn report_psnr(reconstructed_luma : &Vec<f64>, target_luma : &Vec<f64>) {
+	let n = reconstructed_luma.len() as f64;
+	let mse : f64 = reconstructed_luma.iter().zip(target_luma.iter())
+		.map(|(r, t)| (r - t) * (r - t))
+		.sum::<f64>() / n;
+	if mse == 0.0 {
+		eprintln!("PSNR: inf dB");
+	} else {
+		let psnr = 20.0 * 255.0f64.log10() - 10.0 * mse.log10();
+		eprintln!("PSNR: {:.2} dB", psnr);
+	}
+}
+
+// Resizes `img` to the target dimensions and draws it to stdout using the configured draw mode.
+// Shared by the single-image path and the `--stream` playback loop so both stay in sync.
+fn render_image(img : &DynamicImage, target_width : u32, target_height : u32, settings : &Settings, character_image_vector : &Vec<DynamicImage>) {
+	if settings.draw_mode == DrawMode::HalfBlock {
+		render_image_halfblock(img, target_width, target_height, settings);
+		return;
+	}
+
+	let target_region = imageops::resize(img, target_width, target_height, FilterType::CatmullRom); // Nearest/Triangle/CatmullRom/Gaussian/Lanczos3
+
+	// Only populated when --psnr is set: the luma we actually reconstructed per cell vs. the
+	// luma of the (resized) source, so fidelity can be reported once rendering is done.
+	// Block and Char record the sampled color itself as their reconstruction, so this only shows
+	// loss in Art mode, where a glyph's average brightness substitutes for the pixel (see report_psnr).
+	let mut reconstructed_luma = Vec::new();
+	let mut target_luma = Vec::new();
+
+	for (x, y, pixel) in target_region.enumerate_pixels() { // TODO: pixel should be yielding x, y, pixel.
+		// Extract pixel color and, if needed, convert it to grey before passing it off to the draw method.
+		let rgb = extract_rgb(pixel, settings.force_grey, settings.background.unwrap_or((0, 0, 0)));
+
+		// Dispatch draw call.  Sometimes we have to select the best character.
+		match settings.draw_mode {
+			DrawMode::Block => {
+				print_color_character(' ', (0, 0, 0), rgb, settings.use_full_colors);
+				if settings.psnr { reconstructed_luma.push(luma_of_rgb(rgb)); }
+			},
+			DrawMode::Char(c) => {
+				print_color_character(c, rgb, (0, 0, 0), settings.use_full_colors);
+				if settings.psnr { reconstructed_luma.push(luma_of_rgb(rgb)); }
+			},
+			DrawMode::Art => {
+				let best_char = find_best_character(x, y, target_width, target_height, img, character_image_vector);
+				print_color_character(best_char, rgb, (0, 0, 0), settings.use_full_colors);
+				if settings.psnr {
+					let glyph = &character_image_vector[best_char as usize - ' ' as usize];
+					reconstructed_luma.push(mean_glyph_luma(glyph));
+				}
+			},
+			DrawMode::HalfBlock => unreachable!(),
+		};
+		if settings.psnr { target_luma.push(luma_of_rgb(rgb)); }
+
+		// Generate newline if we're at the edge of the output.
+		if x == target_width-1 {
+			print!("\n");
+		}
+	}
+
+	if settings.psnr {
+		report_psnr(&reconstructed_luma, &target_luma);
+	}
+}
+
+// HalfBlock packs two source rows into one terminal cell: the top pixel becomes the foreground
+// color and the bottom pixel becomes the background color of a half-block glyph, doubling the
+// effective vertical resolution for the same number of cells.
+fn render_image_halfblock(img : &DynamicImage, target_width : u32, target_height : u32, settings : &Settings) {
+	let sample_height = target_height * 2;
+	let target_region = imageops::resize(img, target_width, sample_height, FilterType::CatmullRom);
+	let background = settings.background.unwrap_or((0, 0, 0));
+
+	// Only populated when --psnr is set.  HalfBlock records each sampled pixel's composited color
+	// as its reconstruction rather than approximating it with a character match, so reconstruction
+	// equals target by construction here -- same as Block/Char -- but we still have to report it instead
+	// of silently dropping the flag the way this used to.
+	let mut reconstructed_luma = Vec::new();
+	let mut target_luma = Vec::new();
+
+	for y in 0..target_height {
+		for x in 0..target_width {
+			let top = target_region.get_pixel(x, y*2);
+			let top_rgb = extract_rgb(top, settings.force_grey, background);
+			// sample_height is target_height*2, so y*2+1 is always in bounds -- no padding needed.
+			let bottom = target_region.get_pixel(x, y*2 + 1);
+			let bottom_rgb = extract_rgb(bottom, settings.force_grey, background);
+			if settings.use_full_colors {
+				// print_color_character's full-color path only sets the foreground, so emit both here.
+				print!("\u{1B}[38;2;{};{};{}m\u{1B}[48;2;{};{};{}m{}\u{1B}[0m",
+					top_rgb.0, top_rgb.1, top_rgb.2, bottom_rgb.0, bottom_rgb.1, bottom_rgb.2, HALF_BLOCK_CHAR);
+			} else {
+				print_color_character(HALF_BLOCK_CHAR, top_rgb, bottom_rgb, settings.use_full_colors);
+			}
+			if settings.psnr {
+				reconstructed_luma.push(luma_of_rgb(top_rgb));
+				target_luma.push(luma_of_rgb(top_rgb));
+				reconstructed_luma.push(luma_of_rgb(bottom_rgb));
+				target_luma.push(luma_of_rgb(bottom_rgb));
+			}
+		}
+		print!("\n");
+	}
+
+	if settings.psnr {
+		report_psnr(&reconstructed_luma, &target_luma);
+	}
+}
+
+// Reads fixed-size raw frames from stdin (as produced by e.g. `ffmpeg -f rawvideo`) and renders
+// each one in place, homing the cursor between frames so playback overwrites rather than scrolls.
+fn run_stream_mode(settings : &Settings, stream_width : u32, stream_height : u32, format : PixelFormat, character_image_vector : &Vec<DynamicImage>) {
+	let (target_width, target_height) = calculate_target_dimension(settings.output_width, settings.output_height, stream_width, stream_height);
+	let frame_size = (stream_width * stream_height * format.bytes_per_pixel()) as usize;
+	let mut buffer = vec![0u8; frame_size];
+	let mut stdin = io::stdin();
+
+	loop {
+		let mut bytes_read = 0;
+		while bytes_read < frame_size {
+			match stdin.read(&mut buffer[bytes_read..]) {
+				Ok(0) => {
+					if bytes_read == 0 {
+						return; // Clean EOF between frames.
+					} else {
+						return; // Partial final frame -- nothing more we can do, so stop.
+					}
+				},
+				Ok(n) => { bytes_read += n; },
+				Err(problem) => { panic!("Problem reading frame from stream: {}", problem); }
+			}
+		}
+
+		let frame = match format {
+			PixelFormat::Gray8 => DynamicImage::ImageLuma8(ImageBuffer::<Luma<u8>, Vec<u8>>::from_raw(stream_width, stream_height, buffer.clone()).unwrap()),
+			PixelFormat::Rgb24 => DynamicImage::ImageRgb8(ImageBuffer::<Rgb<u8>, Vec<u8>>::from_raw(stream_width, stream_height, buffer.clone()).unwrap()),
+		};
+
+		print!("\u{1B}[H"); // Cursor-home: overwrite the previous frame instead of scrolling.
+		render_image(&frame, target_width, target_height, settings, character_image_vector);
+	}
+}
+
+// Reads the whole input (stdin or the named file) into memory.  We need the raw bytes up front,
+// rather than streaming straight into `image::open`, so we can sniff for an animated GIF before
+// deciding which decode path to take.
+fn read_input_bytes(settings : &Settings) -> Vec<u8> {
+	if settings.input_filename == "" {
+		let mut buffer = Vec::<u8>::new();
+		match io::stdin().read_to_end(&mut buffer) { _ => () };
+		buffer
+	} else {
+		fs::read(&settings.input_filename).unwrap()
+	}
+}
+
+fn is_gif(buffer : &[u8]) -> bool {
+	buffer.len() >= 3 && &buffer[0..3] == b"GIF"
+}
+
+// Decodes every frame of an animated GIF into a plain DynamicImage plus its display delay, so the
+// rest of the pipeline (resize, crop, draw-mode dispatch) doesn't need to know about animation.
+// Uses the `gif` crate directly instead of image's animation decoder: image's AnimationDecoder
+// only exists alongside a GenericImageView-based API that's incompatible with the rest of this
+// file's older, field-access (`.data[n]`) style.  Each decoded frame is composited onto a
+// full-canvas RGBA buffer (frames only cover the region that changed) and wrapped the same way
+// run_stream_mode wraps raw frames, so the rest of the pipeline never has to care.
+fn decode_gif_frames(buffer : &[u8]) -> Vec<(DynamicImage, Duration)> {
+	let mut decoder = gif::Decoder::new(buffer);
+	decoder.set(gif::ColorOutput::RGBA);
+	let mut reader = decoder.read_info().unwrap();
+	let screen_width = reader.width() as u32;
+	let screen_height = reader.height() as u32;
+	let mut canvas = vec![0u8; (screen_width * screen_height * 4) as usize];
+	let mut frames = Vec::new();
+
+	while let Some(frame) = reader.read_next_frame().unwrap() {
+		// "Restore to previous" disposal needs a copy of the canvas from before this frame was
+		// drawn, so we can put it back once this frame's delay has elapsed.
+		let pre_frame_canvas = match frame.dispose {
+			gif::DisposalMethod::Previous => Some(canvas.clone()),
+			_ => None,
+		};
+
+		let frame_width = frame.width as u32;
+		let frame_height = frame.height as u32;
+		for y in 0..frame_height {
+			for x in 0..frame_width {
+				let dest_x = frame.left as u32 + x;
+				let dest_y = frame.top as u32 + y;
+				if dest_x < screen_width && dest_y < screen_height {
+					let src_index = ((y * frame_width + x) * 4) as usize;
+					let dest_index = ((dest_y * screen_width + dest_x) * 4) as usize;
+					// Transparent pixels leave whatever is already on the canvas showing through.
+					if frame.buffer[src_index+3] != 0 {
+						canvas[dest_index..dest_index+4].copy_from_slice(&frame.buffer[src_index..src_index+4]);
+					}
+				}
+			}
+		}
+		let delay_ms = frame.delay as u64 * 10; // GIF delay is in hundredths of a second.
+		let image_buffer = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(screen_width, screen_height, canvas.clone()).unwrap();
+		frames.push((DynamicImage::ImageRgba8(image_buffer), Duration::from_millis(delay_ms)));
+
+		// Apply this frame's disposal method before the next frame is drawn, so the canvas
+		// that the next frame blits onto matches what a real GIF player would show.
+		match frame.dispose {
+			gif::DisposalMethod::Background => {
+				for y in 0..frame_height {
+					for x in 0..frame_width {
+						let dest_x = frame.left as u32 + x;
+						let dest_y = frame.top as u32 + y;
+						if dest_x < screen_width && dest_y < screen_height {
+							let dest_index = ((dest_y * screen_width + dest_x) * 4) as usize;
+							canvas[dest_index..dest_index+4].copy_from_slice(&[0, 0, 0, 0]);
+						}
+					}
+				}
+			},
+			gif::DisposalMethod::Previous => {
+				canvas = pre_frame_canvas.unwrap();
+			},
+			gif::DisposalMethod::Any | gif::DisposalMethod::Keep => {},
+		}
+	}
+	frames
+}
+
+// Plays back a decoded GIF's frames in the terminal, honoring each frame's delay and the
+// configured --loop count (0 = forever).
+fn run_animation_mode(settings : &Settings, frames : &Vec<(DynamicImage, Duration)>, character_image_vector : &Vec<DynamicImage>) {
+	let mut pass = 0;
+	loop {
+		for &(ref frame, delay) in frames.iter() {
+			let (image_width, image_height) = frame.dimensions();
+			let (target_width, target_height) = calculate_target_dimension(settings.output_width, settings.output_height, image_width, image_height);
+			let mut cropped = frame.clone();
+			if let Some(rect) = settings.region {
+				cropped = cropped.crop(rect.0, rect.1, rect.2-rect.0, rect.3-rect.1);
+			}
+
+			print!("\u{1B}[2J\u{1B}[H"); // Clear screen and home the cursor between frames.
+			render_image(&cropped, target_width, target_height, settings, character_image_vector);
+			thread::sleep(delay);
+		}
+
+		pass += 1;
+		if settings.loop_count != 0 && pass >= settings.loop_count {
+			break;
+		}
+	}
+}
+
 fn main() {
 	let arguments: Vec<_> = env::args().collect();
 	let settings = parse_args(arguments);
 
 	if settings.show_help {
 		print_help();
+	} else if let Some((stream_width, stream_height, format)) = settings.stream_mode {
+		let character_image_vector = load_character_set(&settings);
+		run_stream_mode(&settings, stream_width, stream_height, format, &character_image_vector);
 	} else {
-		let mut img = if settings.input_filename == "" { 
-			// Don't do this because it expects a UTF-8 string:
-			//let mut buffer = String::new();
-			//io::stdin().read_to_string(&mut buffer);
-			// This may be an option:
-			//image::load(std::io::BufReader::new(std::io::stdin()))
-			let mut buffer = Vec::<u8>::new();
-			match io::stdin().read_to_end(&mut buffer) { _ => () };
-			match image::load_from_memory(&buffer) {
-				Ok(img) => img,
-				Err(problem) => { panic!("Problem loading image from stream: {}", problem); }
+		let buffer = read_input_bytes(&settings);
+
+		if is_gif(&buffer) {
+			let frames = decode_gif_frames(&buffer);
+			if frames.len() > 1 {
+				let character_image_vector = load_character_set(&settings);
+				run_animation_mode(&settings, &frames, &character_image_vector);
+				return;
 			}
-		} else { 
-			image::open(&Path::new(&settings.input_filename)).unwrap() 
+		}
+
+		let mut img = match image::load_from_memory(&buffer) {
+			Ok(img) => img,
+			Err(problem) => { panic!("Problem loading image: {}", problem); }
 		};
 
 		// Calculate aspect ratio and see if there are any requests outside the image range.
 		let (image_width, image_height) = img.dimensions();
 		//let color = img.color();
 		let (target_width, target_height) = calculate_target_dimension(settings.output_width, settings.output_height, image_width, image_height);
-		
+
 		// Only crop if the rect flag is set.
 		img = match settings.region {
 			Some(rect) => { img.crop(rect.0, rect.1, rect.2-rect.0, rect.3-rect.1) },
 			None => { img },
 		};
-		let target_region = imageops::resize(&img, target_width, target_height, FilterType::CatmullRom); // Nearest/Triangle/CatmullRom/Gaussian/Lanczos3
 
 		// Since we're calling this every pixel, let's preload the comparison NN set for the 'best character' search, but only if the mode is 'Art'.
 		// TODO: Make this optionally loaded.
-		let font_image = image::load(Cursor::new(&include_bytes!("characters.png")[..]), image::PNG).unwrap(); // TODO: MAGIC NUMBER - Make 'characters' a magic number.
-		let character_image_vector = build_character_image_vector(&font_image);
-
-		for (x, y, pixel) in target_region.enumerate_pixels() { // TODO: pixel should be yielding x, y, pixel.
-			// Extract pixel color and, if needed, convert it to grey before passing it off to the draw method.
-			let mut rgb = (pixel.data[0], pixel.data[1], pixel.data[2]);
-			if settings.force_grey {
-				// TODO: Check if already luma and use to_luma.
-				let sum_rgb : u8 = ((pixel.data[0] as u32 + pixel.data[1] as u32 + pixel.data[2] as u32) / 3) as u8;
-				rgb = (sum_rgb, sum_rgb, sum_rgb);
-			}
+		let character_image_vector = load_character_set(&settings);
 
-			// Dispatch draw call.  Sometimes we have to select the best character. 
-			match settings.draw_mode {
-				DrawMode::Block => { print_color_character(' ', (0, 0, 0), rgb, settings.use_full_colors) },
-				DrawMode::Char(c) => { print_color_character(c, rgb, (0, 0, 0), settings.use_full_colors) },
-				DrawMode::Art => { print_color_character(find_best_character(x, y, target_width, target_height, &img, &character_image_vector), rgb, (0, 0, 0), settings.use_full_colors) },
-			};
-
-			// Generate newline if we're at the edge of the output.
-			if x == target_width-1 {
-				print!("\n");
-			}
-		}
+		render_image(&img, target_width, target_height, &settings, &character_image_vector);
 	}
 }